@@ -8,8 +8,9 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::{str, io};
+use std::io::fs::PathExtensions;
 use std::string::String;
 
 use getopts;
@@ -17,7 +18,7 @@ use testing;
 
 use html::escape::Escape;
 use html::markdown;
-use html::markdown::{MarkdownWithToc, find_testable_code, reset_headers};
+use html::markdown::{Markdown, MarkdownWithToc, find_testable_code, reset_headers};
 use test::Collector;
 
 fn load_string(input: &Path) -> io::IoResult<Option<String>> {
@@ -62,6 +63,72 @@ fn extract_leading_metadata<'a>(s: &'a str) -> (Vec<&'a str>, &'a str) {
     (metadata, "")
 }
 
+/// The parsed front matter of a markdown file.
+struct Metadata<'a> {
+    title: &'a str,
+    author: Option<&'a str>,
+    date: Option<&'a str>,
+    css: Vec<&'a str>,
+}
+
+/// Turn the raw `%` lines gathered by `extract_leading_metadata` into a
+/// structured front-matter block.
+///
+/// Recognized lines look like `key: value`, where `key` is one of `title`,
+/// `author`, `date` or `css` (`css` may repeat to add more than one
+/// stylesheet). For backward compatibility, a first line that isn't one of
+/// those forms is still treated as a bare title, matching the old
+/// `% ...TITLE...` convention. Returns `None` if no title was ever found,
+/// whether the file has no `%` lines at all or the `%` lines it does have
+/// are all recognized keys other than `title`.
+fn parse_metadata<'a>(raw: &[&'a str]) -> Option<Metadata<'a>> {
+    if raw.len() == 0 {
+        return None;
+    }
+
+    let mut title = None;
+    let mut author = None;
+    let mut date = None;
+    let mut css = Vec::new();
+
+    for (i, line) in raw.iter().enumerate() {
+        let line = *line;
+        let recognized = line.find(':').and_then(|idx| {
+            match line.slice_to(idx).trim() {
+                "title" => Some(("title", line.slice_from(idx + 1).trim_left())),
+                "author" => Some(("author", line.slice_from(idx + 1).trim_left())),
+                "date" => Some(("date", line.slice_from(idx + 1).trim_left())),
+                "css" => Some(("css", line.slice_from(idx + 1).trim_left())),
+                _ => None,
+            }
+        });
+
+        match recognized {
+            Some(("title", value)) => title = Some(value),
+            Some(("author", value)) => author = Some(value),
+            Some(("date", value)) => date = Some(value),
+            Some(("css", value)) => css.push(value),
+            _ => {
+                if i == 0 && title.is_none() {
+                    title = Some(line);
+                }
+            }
+        }
+    }
+
+    let title = match title {
+        Some(t) => t,
+        None => return None,
+    };
+
+    Some(Metadata {
+        title: title,
+        author: author,
+        date: date,
+        css: css,
+    })
+}
+
 fn load_external_files(names: &[String]) -> Option<String> {
     let mut out = String::new();
     for name in names.iter() {
@@ -71,6 +138,254 @@ fn load_external_files(names: &[String]) -> Option<String> {
     Some(out)
 }
 
+/// Fill in the `{{token}}` placeholders of a user-supplied page template in
+/// a single pass over `template`, so a token's value can't itself be
+/// mistaken for a later token. Unrecognized tokens are left untouched so
+/// that a template can be shared between invocations that only fill in a
+/// subset of the slots.
+fn fill_template(template: &str, tokens: &[(&str, &str)]) -> String {
+    let markers: Vec<(String, &str)> = tokens.iter()
+        .map(|&(name, value)| (format!("{{{{{}}}}}", name), value))
+        .collect();
+
+    let mut out = String::new();
+    let mut rest = template;
+    loop {
+        let mut best: Option<(uint, uint, &str)> = None;
+        for &(ref token, value) in markers.iter() {
+            match rest.find_str(token.as_slice()) {
+                Some(idx) => {
+                    let is_better = match best {
+                        Some((best_idx, _, _)) => idx < best_idx,
+                        None => true,
+                    };
+                    if is_better {
+                        best = Some((idx, token.len(), value));
+                    }
+                }
+                None => {}
+            }
+        }
+        match best {
+            Some((idx, len, value)) => {
+                out.push_str(rest.slice_to(idx));
+                out.push_str(value);
+                rest = rest.slice_from(idx + len);
+            }
+            None => break,
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Slugify a heading's text into an anchor id, with the usual `-1`, `-2`
+/// suffixes for text that repeats earlier in the document.
+fn slugify(title: &str, used: &mut HashMap<String, uint>) -> String {
+    let mut slug = String::new();
+    let mut last_was_dash = true;
+    for c in title.chars() {
+        if c.is_alphanumeric() {
+            slug.push_char(c.to_lowercase());
+            last_was_dash = false;
+        } else if !last_was_dash {
+            slug.push_char('-');
+            last_was_dash = true;
+        }
+    }
+    if slug.as_slice().ends_with("-") {
+        slug.pop();
+    }
+
+    let count = used.find(&slug).map(|&c| c).unwrap_or(0u);
+    used.insert(slug.clone(), count + 1);
+    if count > 0 {
+        format!("{}-{}", slug, count)
+    } else {
+        slug
+    }
+}
+
+/// Render a markdown inline span (no surrounding block tags) to HTML, for
+/// embedding formatted heading text somewhere other than the document body.
+fn render_inline(text: &str) -> String {
+    let rendered = format!("{}", Markdown(text));
+    let trimmed = rendered.as_slice().trim();
+    if trimmed.starts_with("<p>") && trimmed.ends_with("</p>") {
+        trimmed.slice(3, trimmed.len() - 4).to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+/// Walk the raw markdown source and collect its ATX-style (`#`) headings as
+/// `(level, id, text)` triples, for the `--markdown-toc-sidebar` mode.
+///
+/// This is a second, from-scratch traversal rather than something shared
+/// with `html::markdown`, since that module isn't part of this crate's
+/// sources here. `stamp_heading_ids` re-applies the ids collected here onto
+/// the rendered body, so both always agree.
+fn collect_headings(text: &str) -> Vec<(uint, String, String)> {
+    let mut headings = Vec::new();
+    let mut used = HashMap::new();
+    let mut in_fence = false;
+    for line in text.lines() {
+        let trimmed = line.trim_left();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        // A line indented 4 or more spaces is an indented code block, not a
+        // heading, even if it happens to start with `#` (CommonMark).
+        let indent = line.len() - trimmed.len();
+        if indent >= 4 || !trimmed.starts_with("#") {
+            continue;
+        }
+
+        let level = trimmed.chars().take_while(|&c| c == '#').count();
+        if level == 0 || level > 6 {
+            continue;
+        }
+        // An ATX heading needs whitespace after the `#`s (CommonMark); this
+        // also keeps a bare "#hashtag" in prose from being misdetected.
+        let after_hashes = trimmed.slice_from(level);
+        if !after_hashes.starts_with(" ") && !after_hashes.starts_with("\t") {
+            continue;
+        }
+
+        let raw_title = after_hashes.trim();
+        if raw_title.len() == 0 {
+            continue;
+        }
+        let id = slugify(raw_title, &mut used);
+        let title = render_inline(raw_title);
+        headings.push((level, id, title));
+    }
+    headings
+}
+
+/// Remove an existing `id="..."` attribute (and the whitespace before it)
+/// from a tag's attribute string, if it has one.
+fn strip_existing_id(attrs: &str) -> String {
+    let start = match attrs.find_str("id=\"") {
+        Some(i) => i,
+        None => return attrs.to_string(),
+    };
+    let value_start = start + "id=\"".len();
+    let value_end = match attrs.slice_from(value_start).find('"') {
+        Some(i) => value_start + i + 1,
+        None => return attrs.to_string(),
+    };
+    let strip_from = if start > 0 && attrs.char_at(start - 1) == ' ' {
+        start - 1
+    } else {
+        start
+    };
+    format!("{}{}", attrs.slice_to(strip_from), attrs.slice_from(value_end))
+}
+
+/// Overwrite the id of each `<hN>` tag in rendered `content`, in encounter
+/// order, with the id from `headings`, so the sidebar's `href="#id"` links
+/// always resolve to the heading the renderer actually produced. Any `id`
+/// the renderer already assigned the tag is dropped first, so a tag never
+/// ends up with two conflicting `id` attributes.
+fn stamp_heading_ids(content: &str, headings: &[(uint, String, String)]) -> String {
+    let mut out = String::new();
+    let mut rest = content;
+    for &(level, ref id, _) in headings.iter() {
+        let open_tag = format!("<h{}", level);
+        let idx = match rest.find_str(open_tag.as_slice()) {
+            Some(i) => i,
+            None => break,
+        };
+        out.push_str(rest.slice_to(idx));
+
+        let after_open = rest.slice_from(idx + open_tag.len());
+        let tag_end = match after_open.find('>') {
+            Some(i) => i,
+            None => break,
+        };
+        let attrs = strip_existing_id(after_open.slice_to(tag_end));
+
+        out.push_str(open_tag.as_slice());
+        out.push_str(format!(" id=\"{}\"", id).as_slice());
+        out.push_str(attrs.as_slice());
+        out.push_char('>');
+
+        rest = after_open.slice_from(tag_end + 1);
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Render a collected heading list as a standalone `<nav class="sidebar">`
+/// fragment, for injection into the `{toc}` template slot.
+fn render_toc_sidebar(headings: &[(uint, String, String)]) -> String {
+    let mut out = String::new();
+    out.push_str("<nav class=\"sidebar\">\n    <ul class=\"toc\">\n");
+    for &(level, ref id, ref title) in headings.iter() {
+        // `title` is already rendered markdown (see `collect_headings`), so
+        // it's inserted as-is rather than `Escape`d a second time.
+        let item = format!("        <li class=\"toc-level-{}\"><a href=\"#{}\">{}</a></li>\n",
+                            level, id, title);
+        out.push_str(item.as_slice());
+    }
+    out.push_str("    </ul>\n</nav>\n");
+    out
+}
+
+/// Rewrite the `[text](other.md)` links on a single non-fenced line so they
+/// point at the `.html` file that `other.md` is rendered to, for every
+/// `stem` known to be part of the current batch.
+fn rewrite_markdown_links_line(line: &str, stems: &HashSet<String>) -> String {
+    let mut out = line.to_string();
+    for stem in stems.iter() {
+        let from = format!("]({}.md)", stem);
+        let to = format!("]({}.html)", stem);
+        out = out.as_slice().replace(from.as_slice(), to.as_slice());
+
+        let from_frag = format!("]({}.md#", stem);
+        let to_frag = format!("]({}.html#", stem);
+        out = out.as_slice().replace(from_frag.as_slice(), to_frag.as_slice());
+    }
+    out
+}
+
+/// Rewrite inter-document `[text](other.md)` links so they point at the
+/// `.html` file that `other.md` is rendered to, for every `stem` known to
+/// be part of the current batch. Fenced code blocks are left untouched, so
+/// a sample showing someone else's markdown source doesn't get its links
+/// rewritten as if they were real.
+fn rewrite_markdown_links(text: &str, stems: &HashSet<String>) -> String {
+    let mut out = String::new();
+    let mut in_fence = false;
+    let mut first = true;
+    for line in text.lines() {
+        if !first {
+            out.push_char('\n');
+        }
+        first = false;
+
+        let trimmed = line.trim_left();
+        let is_fence_delim = trimmed.starts_with("```") || trimmed.starts_with("~~~");
+        if in_fence || is_fence_delim {
+            out.push_str(line);
+        } else {
+            out.push_str(rewrite_markdown_links_line(line, stems).as_slice());
+        }
+        if is_fence_delim {
+            in_fence = !in_fence;
+        }
+    }
+    if text.ends_with("\n") {
+        out.push_char('\n');
+    }
+    out
+}
+
 /// Render `input` (e.g. "foo.md") into an HTML file in `output`
 /// (e.g. output = "bar" => "bar/foo.html").
 pub fn render(input: &str, mut output: Path, matches: &getopts::Matches) -> int {
@@ -78,19 +393,209 @@ pub fn render(input: &str, mut output: Path, matches: &getopts::Matches) -> int
     output.push(input_p.filestem().unwrap());
     output.set_extension("html");
 
-    let mut css = String::new();
-    for name in matches.opt_strs("markdown-css").iter() {
-        let s = format!("<link rel=\"stylesheet\" type=\"text/css\" href=\"{}\">\n", name);
-        css.push_str(s.as_slice())
+    let input_str = load_or_return!(input, 1, 2);
+    render_string(input, input_str, output, matches, None)
+}
+
+/// Recursively collect every `.md` file under `dir`, paired with its path
+/// relative to `dir` (slashes, no extension) to use as its stem, e.g. a
+/// file at `dir/guide/intro.md` collects as `("guide/intro", ..)`. Entries
+/// at each directory level are visited in filename order so the result (and
+/// the index `render_dir` builds from it) is reproducible across runs.
+fn find_md_files(dir: &Path, prefix: &str, out: &mut Vec<(Path, String)>) -> io::IoResult<()> {
+    let mut entries = try!(io::fs::readdir(dir));
+    entries.sort_by(|a, b| a.filename_str().cmp(&b.filename_str()));
+
+    for entry in entries.iter() {
+        let name = entry.filename_str().unwrap();
+        if entry.is_dir() {
+            let sub_prefix = if prefix.len() == 0 {
+                name.to_string()
+            } else {
+                format!("{}/{}", prefix, name)
+            };
+            try!(find_md_files(entry, sub_prefix.as_slice(), out));
+        } else if entry.extension_str() == Some("md") {
+            let filestem = entry.filestem_str().unwrap();
+            let stem = if prefix.len() == 0 {
+                filestem.to_string()
+            } else {
+                format!("{}/{}", prefix, filestem)
+            };
+            out.push((entry.clone(), stem));
+        }
     }
+    Ok(())
+}
 
-    let input_str = load_or_return!(input, 1, 2);
+/// Render every `.md` file under `dir`, recursing into subdirectories,
+/// rewriting links between them to point at the generated `.html`
+/// counterparts and emitting an `index.html` that lists every rendered
+/// title.
+pub fn render_dir(dir: &str, output: Path, matches: &getopts::Matches) -> int {
+    let mut md_files = Vec::new();
+    match find_md_files(&Path::new(dir), "", &mut md_files) {
+        Err(e) => {
+            let _ = writeln!(&mut io::stderr(),
+                             "error reading `{}`: {}", dir, e);
+            return 1;
+        }
+        Ok(()) => {}
+    }
+    md_files.sort_by(|&(_, ref a), &(_, ref b)| a.cmp(b));
+
+    let stems: HashSet<String> = md_files.iter()
+        .map(|&(_, ref stem)| stem.clone())
+        .collect();
+
+    let mut index = Vec::new();
+    for &(ref path, ref stem) in md_files.iter() {
+        let path_str = path.as_str().unwrap();
+        let input_str = load_or_return!(path_str, 1, 2);
+        let rewritten = rewrite_markdown_links(input_str.as_slice(), &stems);
+
+        // A file with no front matter still gets rendered in batch mode,
+        // using its stem (its path relative to `dir`) as the title, rather than aborting the whole
+        // directory over one missing `%` line.
+        let (raw_metadata, _) = extract_leading_metadata(rewritten.as_slice());
+        let title = match parse_metadata(raw_metadata.as_slice()) {
+            Some(m) => m.title.to_string(),
+            None => stem.clone(),
+        };
+
+        let mut page_output = output.clone();
+        page_output.push(stem.as_slice());
+        page_output.set_extension("html");
+
+        match io::fs::mkdir_recursive(&page_output.dir_path(), io::USER_RWX) {
+            Err(e) => {
+                let _ = writeln!(&mut io::stderr(),
+                                 "error creating `{}`: {}", page_output.dir_path().display(), e);
+                return 1;
+            }
+            Ok(()) => {}
+        }
+
+        let status = render_string(path_str, rewritten, page_output, matches,
+                                    Some(stem.as_slice()));
+        if status != 0 {
+            return status;
+        }
+
+        index.push((stem.clone(), title));
+    }
+
+    render_index(index.as_slice(), output, matches)
+}
+
+/// Emit a shared navigation page listing every page rendered by `render_dir`,
+/// through the same `write_page` helper a single-document render uses, so
+/// `--markdown-css`, `--markdown-template` and the header/footer options
+/// apply to the index exactly as they do to every other generated page.
+fn render_index(pages: &[(String, String)], mut output: Path, matches: &getopts::Matches) -> int {
+    output.push("index.html");
+
+    let mut items = String::new();
+    for &(ref stem, ref title) in pages.iter() {
+        let item = format!("        <li><a href=\"{}.html\">{}</a></li>\n",
+                            stem, Escape(title.as_slice()));
+        items.push_str(item.as_slice());
+    }
+    let content = format!("<ul class=\"index\">\n{}    </ul>", items);
+
+    let playground = matches.opt_str("markdown-playground-url").unwrap_or("".to_string());
+    let no_extra_css: Vec<&str> = Vec::new();
+
+    write_page(output, matches, "Index", content.as_slice(), "", "", "",
+               playground.as_slice(), no_extra_css.as_slice())
+}
+
+/// Render a single already-loaded markdown document to `output`, the final
+/// `.html` file path (shared by `render` and `render_dir`).
+///
+/// `source` is the path used only for error messages. `default_title`, when
+/// given, is used in place of a missing `% title` instead of failing outright.
+fn render_string(source: &str, input_str: String, output: Path, matches: &getopts::Matches,
+                  default_title: Option<&str>) -> int {
     let playground = matches.opt_str("markdown-playground-url");
     if playground.is_some() {
         markdown::playground_krate.replace(Some(None));
     }
     let playground = playground.unwrap_or("".to_string());
 
+    let (raw_metadata, text) = extract_leading_metadata(input_str.as_slice());
+    let parsed = parse_metadata(raw_metadata.as_slice());
+    let (title, author, date, meta_css) = match parsed {
+        Some(ref m) => (m.title.to_string(), m.author, m.date, m.css.clone()),
+        None => match default_title {
+            Some(t) => (t.to_string(), None, None, Vec::new()),
+            None => {
+                let _ = writeln!(&mut io::stderr(),
+                                 "invalid markdown file `{}`: expecting initial line with \
+                                  `% ...TITLE...`", source);
+                return 5;
+            }
+        },
+    };
+
+    let author_meta = match author {
+        Some(a) => format!("<meta name=\"author\" content=\"{}\">\n", Escape(a)),
+        None => String::new(),
+    };
+    let date_html = match date {
+        Some(d) => format!("<span class=\"sub-header-date\">{}</span>", Escape(d)),
+        None => String::new(),
+    };
+
+    reset_headers();
+
+    let title = format!("{}", Escape(title.as_slice()));
+
+    let toc_sidebar = matches.opt_present("markdown-toc-sidebar");
+    let (content, toc) = if toc_sidebar {
+        let headings = collect_headings(text);
+        let rendered = format!("{}", Markdown(text));
+        let content = stamp_heading_ids(rendered.as_slice(), headings.as_slice());
+        (content, render_toc_sidebar(headings.as_slice()))
+    } else {
+        (format!("{}", MarkdownWithToc(text)), String::new())
+    };
+
+    write_page(output, matches, title.as_slice(), content.as_slice(), toc.as_slice(),
+               author_meta.as_slice(), date_html.as_slice(), playground.as_slice(),
+               meta_css.as_slice())
+}
+
+/// Assemble and write the final HTML page, honoring `--markdown-css`,
+/// `--markdown-template`, `--markdown-in-header` and
+/// `--markdown-before/after-content`. Shared by `render_string` and
+/// `render_index` so every generated page goes through the same skeleton.
+fn write_page(mut output: Path,
+              matches: &getopts::Matches,
+              title: &str,
+              content: &str,
+              toc: &str,
+              author_meta: &str,
+              date_html: &str,
+              playground: &str,
+              extra_css: &[&str]) -> int {
+    let mut css = String::new();
+    for name in matches.opt_strs("markdown-css").iter() {
+        let s = format!("<link rel=\"stylesheet\" type=\"text/css\" href=\"{}\">\n",
+                         Escape(name.as_slice()));
+        css.push_str(s.as_slice())
+    }
+    for name in extra_css.iter() {
+        let s = format!("<link rel=\"stylesheet\" type=\"text/css\" href=\"{}\">\n",
+                         Escape(*name));
+        css.push_str(s.as_slice())
+    }
+
+    let template = match matches.opt_str("markdown-template") {
+        Some(t) => Some(load_or_return!(t.as_slice(), 7, 8)),
+        None => None,
+    };
+
     let (in_header, before_content, after_content) =
         match (load_external_files(matches.opt_strs("markdown-in-header")
                                           .move_iter()
@@ -121,24 +626,31 @@ pub fn render(input: &str, mut output: Path, matches: &getopts::Matches) -> int
         Ok(f) => f
     };
 
-    let (metadata, text) = extract_leading_metadata(input_str.as_slice());
-    if metadata.len() == 0 {
-        let _ = writeln!(&mut io::stderr(),
-                         "invalid markdown file: expecting initial line with `% ...TITLE...`");
-        return 5;
-    }
-    let title = metadata.get(0).as_slice();
-
-    reset_headers();
-
-    let err = write!(
-        &mut out,
-        r#"<!DOCTYPE html>
+    let err = match template {
+        Some(ref t) => {
+            let page = fill_template(t.as_slice(), [
+                ("title", title),
+                ("content", content),
+                ("toc", toc),
+                ("css", css.as_slice()),
+                ("in_header", in_header.as_slice()),
+                ("before_content", before_content.as_slice()),
+                ("after_content", after_content.as_slice()),
+                ("playground_url", playground),
+                ("author", author_meta),
+                ("date", date_html),
+            ].as_slice());
+            write!(&mut out, "{}", page)
+        }
+        None => write!(
+            &mut out,
+            r#"<!DOCTYPE html>
 <html lang="en">
 <head>
     <meta charset="utf-8">
     <meta name="generator" content="rustdoc">
     <title>{title}</title>
+    {author_meta}
 
     {css}
     {in_header}
@@ -153,6 +665,8 @@ pub fn render(input: &str, mut output: Path, matches: &getopts::Matches) -> int
 
     {before_content}
     <h1 class="title">{title}</h1>
+    {date}
+    {toc}
     {text}
     <script type="text/javascript">
         window.playgroundUrl = "{playground}";
@@ -160,14 +674,18 @@ pub fn render(input: &str, mut output: Path, matches: &getopts::Matches) -> int
     {after_content}
 </body>
 </html>"#,
-        title = Escape(title),
-        css = css,
-        in_header = in_header,
-        before_content = before_content,
-        text = MarkdownWithToc(text),
-        after_content = after_content,
-        playground = playground,
-        );
+            title = title,
+            author_meta = author_meta,
+            css = css,
+            in_header = in_header,
+            before_content = before_content,
+            date = date_html,
+            toc = toc,
+            text = content,
+            after_content = after_content,
+            playground = playground,
+            ),
+    };
 
     match err {
         Err(e) => {
@@ -190,3 +708,133 @@ pub fn test(input: &str, libs: HashSet<Path>, mut test_args: Vec<String>) -> int
     testing::test_main(test_args.as_slice(), collector.tests);
     0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{fill_template, parse_metadata, extract_leading_metadata, slugify,
+                 collect_headings, rewrite_markdown_links, stamp_heading_ids};
+    use std::collections::{HashMap, HashSet};
+
+    #[test]
+    fn fill_template_substitutes_every_token() {
+        let page = fill_template("<title>{{title}}</title>{{content}}",
+                                  [("title", "Hi"), ("content", "<p>body</p>")].as_slice());
+        assert_eq!(page.as_slice(), "<title>Hi</title><p>body</p>");
+    }
+
+    #[test]
+    fn fill_template_leaves_unknown_tokens_untouched() {
+        let page = fill_template("{{title}} {{nope}}", [("title", "Hi")].as_slice());
+        assert_eq!(page.as_slice(), "Hi {{nope}}");
+    }
+
+    #[test]
+    fn fill_template_does_not_reprocess_substituted_values() {
+        // A value that happens to contain another token's placeholder text
+        // must not be substituted a second time.
+        let page = fill_template("{{content}}{{css}}",
+                                  [("content", "see {{css}} below"), ("css", "STYLE")].as_slice());
+        assert_eq!(page.as_slice(), "see {{css}} belowSTYLE");
+    }
+
+    #[test]
+    fn parse_metadata_bare_first_line_is_title() {
+        let (raw, _) = extract_leading_metadata("% My Title\nbody");
+        let meta = parse_metadata(raw.as_slice()).unwrap();
+        assert_eq!(meta.title, "My Title");
+        assert!(meta.author.is_none());
+    }
+
+    #[test]
+    fn parse_metadata_recognizes_key_value_lines() {
+        let (raw, _) = extract_leading_metadata(
+            "% title: My Title\n% author: jane\n% date: 2015-01-01\n% css: a.css\n% css: b.css\nbody");
+        let meta = parse_metadata(raw.as_slice()).unwrap();
+        assert_eq!(meta.title, "My Title");
+        assert_eq!(meta.author, Some("jane"));
+        assert_eq!(meta.date, Some("2015-01-01"));
+        assert_eq!(meta.css, vec!["a.css", "b.css"]);
+    }
+
+    #[test]
+    fn parse_metadata_none_without_a_title() {
+        let (raw, _) = extract_leading_metadata("% author: jane\nbody");
+        assert!(parse_metadata(raw.as_slice()).is_none());
+        let (raw, _) = extract_leading_metadata("body only, no metadata");
+        assert!(parse_metadata(raw.as_slice()).is_none());
+    }
+
+    #[test]
+    fn slugify_dedupes_repeated_titles() {
+        let mut used = HashMap::new();
+        assert_eq!(slugify("Intro", &mut used).as_slice(), "intro");
+        assert_eq!(slugify("Intro", &mut used).as_slice(), "intro-1");
+        assert_eq!(slugify("Intro!!", &mut used).as_slice(), "intro-2");
+    }
+
+    #[test]
+    fn slugify_strips_non_alphanumeric_runs() {
+        let mut used = HashMap::new();
+        assert_eq!(slugify("Using `unsafe`, carefully", &mut used).as_slice(),
+                   "using-unsafe-carefully");
+    }
+
+    #[test]
+    fn collect_headings_requires_atx_whitespace() {
+        let headings = collect_headings("#nope\n\n# Real Heading\n");
+        assert_eq!(headings.len(), 1);
+        let &(level, _, ref title) = &headings[0];
+        assert_eq!(level, 1u);
+        assert_eq!(title.as_slice(), "Real Heading");
+    }
+
+    #[test]
+    fn collect_headings_skips_fenced_code() {
+        let headings = collect_headings("```\n# not a heading\n```\n# Real Heading\n");
+        assert_eq!(headings.len(), 1);
+        let &(_, _, ref title) = &headings[0];
+        assert_eq!(title.as_slice(), "Real Heading");
+    }
+
+    #[test]
+    fn collect_headings_skips_indented_code() {
+        let headings = collect_headings("    # fixme: not a heading\n\n# Real Heading\n");
+        assert_eq!(headings.len(), 1);
+        let &(_, _, ref title) = &headings[0];
+        assert_eq!(title.as_slice(), "Real Heading");
+    }
+
+    #[test]
+    fn rewrite_markdown_links_rewrites_plain_and_fragment_links() {
+        let mut stems = HashSet::new();
+        stems.insert("other".to_string());
+        let text = "[a](other.md) and [b](other.md#section)";
+        let rewritten = rewrite_markdown_links(text, &stems);
+        assert_eq!(rewritten.as_slice(),
+                   "[a](other.html) and [b](other.html#section)");
+    }
+
+    #[test]
+    fn rewrite_markdown_links_ignores_fenced_code() {
+        let mut stems = HashSet::new();
+        stems.insert("other".to_string());
+        let text = "```\n[a](other.md)\n```\n";
+        let rewritten = rewrite_markdown_links(text, &stems);
+        assert_eq!(rewritten.as_slice(), text);
+    }
+
+    #[test]
+    fn stamp_heading_ids_inserts_id_on_plain_tag() {
+        let headings = vec![(1u, "intro".to_string(), "Intro".to_string())];
+        let out = stamp_heading_ids("<h1>Intro</h1>", headings.as_slice());
+        assert_eq!(out.as_slice(), "<h1 id=\"intro\">Intro</h1>");
+    }
+
+    #[test]
+    fn stamp_heading_ids_replaces_an_existing_id() {
+        let headings = vec![(1u, "intro".to_string(), "Intro".to_string())];
+        let out = stamp_heading_ids("<h1 id=\"renderer-assigned\" class=\"title\">Intro</h1>",
+                                     headings.as_slice());
+        assert_eq!(out.as_slice(), "<h1 id=\"intro\" class=\"title\">Intro</h1>");
+    }
+}